@@ -1,10 +1,54 @@
-use crate::db::DbConnection;
+use crate::db::{self, DbPool};
+use crate::error::AppError;
 use bcrypt::{hash, verify, DEFAULT_COST};
-use chrono::Utc;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use rand::Rng;
 use rusqlite::params;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+const ACCESS_TOKEN_TTL_SECS: i64 = 15 * 60;
+const REFRESH_TOKEN_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Upper bound on the `expires_in_secs` a caller can request for a scoped
+/// token via `/tokens`, so a delegated token can't be minted to effectively
+/// never expire.
+pub const MAX_SCOPED_TOKEN_TTL_SECS: i64 = 24 * 60 * 60;
+
+pub const SCOPE_FILES_READ: &str = "files:read";
+pub const SCOPE_FILES_WRITE: &str = "files:write";
+pub const SCOPE_FILES_PUBLIC: &str = "files:public";
+
+/// Scopes granted to a full login/register session, as opposed to a narrowly
+/// delegated token minted via `create_scoped_token`.
+const ALL_SCOPES: &[&str] = &[SCOPE_FILES_READ, SCOPE_FILES_WRITE, SCOPE_FILES_PUBLIC];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub scopes: Vec<String>,
+    pub iat: usize,
+    pub exp: usize,
+}
+
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+/// The authenticated identity and scopes carried by a verified access token.
+pub struct AuthContext {
+    pub user_id: Uuid,
+    pub scopes: Vec<String>,
+}
+
+impl AuthContext {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
 pub fn hash_password(password: &str) -> String {
     hash(password, DEFAULT_COST).unwrap()
 }
@@ -13,33 +57,141 @@ pub fn verify_password(password: &str, hash: &str) -> bool {
     verify(password, hash).unwrap_or(false)
 }
 
-pub fn generate_token() -> String {
+fn jwt_secret() -> String {
+    let secret = std::env::var("JWT_SECRET")
+        .expect("JWT_SECRET must be set; refusing to start with a guessable signing key");
+    if secret.is_empty() {
+        panic!("JWT_SECRET must not be empty");
+    }
+    secret
+}
+
+/// Panics at startup if no JWT signing secret is configured, so a missing
+/// `JWT_SECRET` fails loudly at boot instead of silently signing every
+/// token with a publicly-known default.
+pub fn ensure_jwt_secret_configured() {
+    jwt_secret();
+}
+
+fn generate_refresh_token() -> String {
     rand::thread_rng()
         .sample_iter(&rand::distributions::Alphanumeric)
-        .take(32)
+        .take(48)
         .map(char::from)
         .collect()
 }
 
-pub async fn create_auth_token(conn: &DbConnection, user_id: &Uuid) -> Result<String, rusqlite::Error> {
-    let token = generate_token();
+/// Mints a signed access token for `user_id` carrying the given scopes, valid for `ttl_secs`.
+/// Used both for full sessions and for narrowly delegated tokens minted via `/tokens`.
+pub fn create_scoped_token(
+    user_id: &Uuid,
+    scopes: &[String],
+    ttl_secs: i64,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let now = Utc::now();
+    let claims = Claims {
+        sub: user_id.to_string(),
+        scopes: scopes.to_vec(),
+        iat: now.timestamp() as usize,
+        exp: (now + Duration::seconds(ttl_secs)).timestamp() as usize,
+    };
 
-    conn.lock().await.execute(
-        "INSERT INTO auth_tokens (token, user_id, created_at) VALUES (?, ?, ?)",
-        params![token, user_id.to_string(), now.to_rfc3339()],
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret().as_bytes()),
+    )
+}
+
+/// Mints a short-lived signed access token carrying the user id and full account scopes.
+pub fn create_access_token(user_id: &Uuid) -> Result<String, jsonwebtoken::errors::Error> {
+    let scopes: Vec<String> = ALL_SCOPES.iter().map(|s| s.to_string()).collect();
+    create_scoped_token(user_id, &scopes, ACCESS_TOKEN_TTL_SECS)
+}
+
+/// Verifies and decodes an access token, returning the authenticated user id and scopes.
+pub fn verify_access_token(token: &str) -> Result<AuthContext, jsonwebtoken::errors::Error> {
+    let data = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(jwt_secret().as_bytes()),
+        &Validation::default(),
     )?;
 
+    let user_id = Uuid::parse_str(&data.claims.sub)
+        .map_err(|_| jsonwebtoken::errors::Error::from(jsonwebtoken::errors::ErrorKind::InvalidSubject))?;
+
+    Ok(AuthContext {
+        user_id,
+        scopes: data.claims.scopes,
+    })
+}
+
+/// Persists a long-lived opaque refresh token for the user.
+pub async fn create_refresh_token(pool: &DbPool, user_id: &Uuid) -> Result<String, AppError> {
+    let token = generate_refresh_token();
+    let now = Utc::now();
+    let expires_at = now + Duration::seconds(REFRESH_TOKEN_TTL_SECS);
+
+    let token_clone = token.clone();
+    let user_id = user_id.to_string();
+    db::with_conn(pool, move |conn| {
+        conn.execute(
+            "INSERT INTO refresh_tokens (token, user_id, created_at, expires_at) VALUES (?, ?, ?, ?)",
+            params![token_clone, user_id, now.to_rfc3339(), expires_at.to_rfc3339()],
+        )
+    })
+    .await?;
+
     Ok(token)
 }
 
-pub async fn verify_auth_token(conn: &DbConnection, token: &str) -> Result<Uuid, rusqlite::Error> {
-    conn.lock().await.query_row(
-        "SELECT user_id FROM auth_tokens WHERE token = ?",
-        [token],
-        |row| {
-            let user_id: String = row.get(0)?;
-            Ok(Uuid::parse_str(&user_id).unwrap())
-        },
-    )
-}
\ No newline at end of file
+/// Issues a fresh access/refresh pair for the user, e.g. on register or login.
+pub async fn create_token_pair(pool: &DbPool, user_id: &Uuid) -> Result<TokenPair, AppError> {
+    let access_token = create_access_token(user_id).expect("failed to sign access token");
+    let refresh_token = create_refresh_token(pool, user_id).await?;
+
+    Ok(TokenPair {
+        access_token,
+        refresh_token,
+    })
+}
+
+/// Looks up a refresh token and returns its owner, rejecting expired ones.
+pub async fn verify_refresh_token(pool: &DbPool, token: &str) -> Result<Uuid, AppError> {
+    let token = token.to_string();
+    let (user_id, expires_at): (String, String) = db::with_conn(pool, move |conn| {
+        conn.query_row(
+            "SELECT user_id, expires_at FROM refresh_tokens WHERE token = ?",
+            [token],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    })
+    .await?;
+
+    let expires_at = db::column_timestamp(1, &expires_at)?;
+    if expires_at < Utc::now() {
+        return Err(AppError::InvalidToken);
+    }
+
+    Ok(db::column_uuid(0, &user_id)?)
+}
+
+/// Redeems a refresh token for a new token pair, rotating out the old refresh token.
+///
+/// The `DELETE` is the single-use gate: if two requests race on the same
+/// token, only one of them deletes the row, so only one mints a new pair.
+pub async fn rotate_refresh_token(pool: &DbPool, old_token: &str) -> Result<TokenPair, AppError> {
+    let user_id = verify_refresh_token(pool, old_token).await?;
+
+    let old_token = old_token.to_string();
+    let deleted = db::with_conn(pool, move |conn| {
+        conn.execute("DELETE FROM refresh_tokens WHERE token = ?", [old_token])
+    })
+    .await?;
+
+    if deleted != 1 {
+        return Err(AppError::InvalidToken);
+    }
+
+    create_token_pair(pool, &user_id).await
+}
@@ -0,0 +1,18 @@
+use byte_unit::Byte;
+
+/// Default storage quota granted to new users, overridable via `DEFAULT_QUOTA`
+/// (a human-friendly size string such as `"5 GiB"`).
+pub fn default_quota_bytes() -> u64 {
+    let raw = std::env::var("DEFAULT_QUOTA").unwrap_or_else(|_| "5 GiB".to_string());
+    Byte::parse_str(&raw, true)
+        .unwrap_or_else(|_| panic!("DEFAULT_QUOTA must be a valid byte size, e.g. \"5 GiB\", got {raw:?}"))
+        .as_u64()
+}
+
+/// Maximum number of pooled SQLite connections, overridable via `DB_POOL_SIZE`.
+pub fn db_pool_size() -> u32 {
+    std::env::var("DB_POOL_SIZE")
+        .ok()
+        .and_then(|raw| raw.parse().ok())
+        .unwrap_or(10)
+}
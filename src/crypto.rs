@@ -0,0 +1,64 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::RngCore;
+
+const NONCE_LEN: usize = 12;
+
+fn master_key() -> [u8; 32] {
+    let hex_key = std::env::var("FILE_ENCRYPTION_KEY")
+        .expect("FILE_ENCRYPTION_KEY must be set to a 64-character hex string (e.g. `openssl rand -hex 32`); refusing to start with an implicit all-zero key");
+    let bytes = hex::decode(hex_key).expect("FILE_ENCRYPTION_KEY must be 64 hex chars");
+    bytes.try_into().expect("FILE_ENCRYPTION_KEY must decode to 32 bytes")
+}
+
+/// Panics at startup if no encryption key is configured, so a missing
+/// `FILE_ENCRYPTION_KEY` fails loudly at boot instead of silently encrypting
+/// every file with an all-zero key.
+pub fn ensure_key_configured() {
+    master_key();
+}
+
+fn cipher() -> Aes256Gcm {
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&master_key()))
+}
+
+/// Encrypts `plaintext` with AES-256-GCM, binding `associated_data` (e.g. the file id)
+/// to the ciphertext. Returns `nonce || ciphertext || tag` ready to write to disk.
+pub fn encrypt_file(plaintext: &[u8], associated_data: &[u8]) -> Vec<u8> {
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let ciphertext = cipher()
+        .encrypt(
+            nonce,
+            aes_gcm::aead::Payload {
+                msg: plaintext,
+                aad: associated_data,
+            },
+        )
+        .expect("AES-256-GCM encryption failed");
+
+    let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(&nonce_bytes);
+    out.extend_from_slice(&ciphertext);
+    out
+}
+
+/// Reverses `encrypt_file`, rejecting the payload if the tag or associated data don't match.
+pub fn decrypt_file(data: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, aes_gcm::Error> {
+    if data.len() < NONCE_LEN {
+        return Err(aes_gcm::Error);
+    }
+
+    let (nonce_bytes, ciphertext) = data.split_at(NONCE_LEN);
+    let nonce = Nonce::from_slice(nonce_bytes);
+
+    cipher().decrypt(
+        nonce,
+        aes_gcm::aead::Payload {
+            msg: ciphertext,
+            aad: associated_data,
+        },
+    )
+}
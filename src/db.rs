@@ -1,11 +1,21 @@
-use rusqlite::{Connection, Result};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use crate::error::AppError;
+use chrono::{DateTime, Utc};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+use uuid::Uuid;
 
-pub type DbConnection = Arc<Mutex<Connection>>;
+pub type DbPool = r2d2::Pool<SqliteConnectionManager>;
 
-pub fn establish_connection() -> Result<DbConnection> {
-    let conn = Connection::open("file_cdn.db")?;
+pub fn establish_connection() -> Result<DbPool, Box<dyn std::error::Error>> {
+    let manager = SqliteConnectionManager::file("file_cdn.db");
+    let pool = DbPool::builder()
+        .max_size(crate::config::db_pool_size())
+        .build(manager)?;
+
+    let conn = pool.get()?;
+    // WAL lets readers proceed while a writer is in progress, which matters
+    // once queries are handed out to a pool of concurrent connections.
+    conn.execute_batch("PRAGMA journal_mode = WAL;")?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS users (
@@ -13,6 +23,8 @@ pub fn establish_connection() -> Result<DbConnection> {
             username TEXT NOT NULL UNIQUE,
             email TEXT NOT NULL UNIQUE,
             password_hash TEXT NOT NULL,
+            quota_bytes INTEGER NOT NULL,
+            used_bytes INTEGER NOT NULL DEFAULT 0,
             created_at TEXT NOT NULL,
             updated_at TEXT NOT NULL
         )",
@@ -25,8 +37,10 @@ pub fn establish_connection() -> Result<DbConnection> {
             user_id TEXT NOT NULL,
             filename TEXT NOT NULL,
             file_path TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL DEFAULT 0,
             is_public INTEGER NOT NULL,
             public_url TEXT,
+            public_slug TEXT UNIQUE,
             created_at TEXT NOT NULL,
             updated_at TEXT NOT NULL,
             FOREIGN KEY (user_id) REFERENCES users (id)
@@ -35,14 +49,51 @@ pub fn establish_connection() -> Result<DbConnection> {
     )?;
 
     conn.execute(
-        "CREATE TABLE IF NOT EXISTS auth_tokens (
+        "CREATE TABLE IF NOT EXISTS refresh_tokens (
             token TEXT PRIMARY KEY,
             user_id TEXT NOT NULL,
             created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
             FOREIGN KEY (user_id) REFERENCES users (id)
         )",
         [],
     )?;
 
-    Ok(Arc::new(Mutex::new(conn)))
-}
\ No newline at end of file
+    Ok(pool)
+}
+
+/// Runs `f` against a pooled connection on a blocking-task thread, so a slow
+/// query blocks only that thread instead of stalling the async runtime.
+///
+/// A pool-checkout timeout (e.g. the pool exhausted under load) or a panic
+/// inside `f` is surfaced as an `AppError` rather than unwinding the
+/// request-handling task.
+pub async fn with_conn<F, T>(pool: &DbPool, f: F) -> Result<T, AppError>
+where
+    F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || -> Result<T, AppError> {
+        let conn = pool.get()?;
+        Ok(f(&conn)?)
+    })
+    .await
+    .map_err(|err| AppError::Io(std::io::Error::new(std::io::ErrorKind::Other, err.to_string())))?
+}
+
+/// Parses a UUID stored as TEXT, surfacing a corrupt value as a query error
+/// instead of panicking the blocking task.
+pub fn column_uuid(col: usize, value: &str) -> rusqlite::Result<Uuid> {
+    Uuid::parse_str(value).map_err(|err| {
+        rusqlite::Error::FromSqlConversionFailure(col, rusqlite::types::Type::Text, Box::new(err))
+    })
+}
+
+/// Parses an RFC 3339 timestamp stored as TEXT, surfacing a corrupt value as
+/// a query error instead of panicking the blocking task.
+pub fn column_timestamp(col: usize, value: &str) -> rusqlite::Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(col, rusqlite::types::Type::Text, Box::new(err)))
+}
@@ -0,0 +1,77 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+/// A unified error type for handlers so every failure path returns the same
+/// `{ "status", "message" }` JSON body instead of hand-rolled status/string tuples.
+#[derive(Debug)]
+pub enum AppError {
+    Unauthorized,
+    MissingToken,
+    InvalidToken,
+    Forbidden(String),
+    NotFound,
+    Conflict(String),
+    PayloadTooLarge(String),
+    Database(rusqlite::Error),
+    Io(std::io::Error),
+    Pool(r2d2::Error),
+    Validation(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    status: u16,
+    message: String,
+}
+
+impl AppError {
+    fn status_and_message(&self) -> (StatusCode, String) {
+        match self {
+            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()),
+            AppError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token".to_string()),
+            AppError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token".to_string()),
+            AppError::Forbidden(message) => (StatusCode::FORBIDDEN, message.clone()),
+            AppError::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
+            AppError::Conflict(message) => (StatusCode::CONFLICT, message.clone()),
+            AppError::PayloadTooLarge(message) => (StatusCode::PAYLOAD_TOO_LARGE, message.clone()),
+            AppError::Database(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {err}")),
+            AppError::Io(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("I/O error: {err}")),
+            AppError::Pool(err) => (StatusCode::SERVICE_UNAVAILABLE, format!("Database pool exhausted: {err}")),
+            AppError::Validation(message) => (StatusCode::BAD_REQUEST, message.clone()),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, message) = self.status_and_message();
+        (
+            status,
+            Json(ErrorBody {
+                status: status.as_u16(),
+                message,
+            }),
+        )
+            .into_response()
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(err: rusqlite::Error) -> Self {
+        AppError::Database(err)
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        AppError::Io(err)
+    }
+}
+
+impl From<r2d2::Error> for AppError {
+    fn from(err: r2d2::Error) -> Self {
+        AppError::Pool(err)
+    }
+}
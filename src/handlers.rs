@@ -1,23 +1,45 @@
-use crate::auth::{create_auth_token, hash_password, verify_auth_token, verify_password};
-use crate::db::DbConnection;
-use crate::models::{File, User};
+use crate::auth::{
+    create_scoped_token, create_token_pair, hash_password, rotate_refresh_token, verify_access_token,
+    verify_password, AuthContext, MAX_SCOPED_TOKEN_TTL_SECS, SCOPE_FILES_PUBLIC, SCOPE_FILES_READ,
+    SCOPE_FILES_WRITE,
+};
+use crate::db;
+use crate::error::AppError;
+use crate::images::{self, TransformParams};
+use crate::models::File;
+use crate::AppState;
 use axum::{
     body::Body,
-    extract::{Multipart, Path, State},
+    extract::{Multipart, Path, Query, State},
     http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use rusqlite::params;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::fs;
-use tokio::sync::Mutex;
-use tokio_util::io::ReaderStream;
+use tokio::io::AsyncReadExt;
 use uuid::Uuid;
 
+/// Verifies the bearer token on a request and returns the caller's identity and scopes.
+fn authenticate(headers: &HeaderMap) -> Result<AuthContext, AppError> {
+    let token = headers
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(AppError::MissingToken)?;
+
+    verify_access_token(token).map_err(|_| AppError::InvalidToken)
+}
+
+/// Rejects the request unless the authenticated token carries `scope`.
+fn require_scope(auth: &AuthContext, scope: &str) -> Result<(), AppError> {
+    if auth.has_scope(scope) {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden(format!("Missing required scope: {scope}")))
+    }
+}
+
 #[derive(Deserialize)]
 pub struct RegisterUser {
     username: String,
@@ -32,241 +54,499 @@ pub struct LoginUser {
 }
 
 #[derive(Serialize)]
-pub struct AuthToken {
-    token: String,
+pub struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    refresh_token: String,
 }
 
 pub async fn register_user(
-    State(conn): State<DbConnection>,
+    State(state): State<AppState>,
     Json(user_data): Json<RegisterUser>,
-) -> impl IntoResponse {
+) -> Result<impl IntoResponse, AppError> {
     let password_hash = hash_password(&user_data.password);
     let id = Uuid::new_v4();
     let now = Utc::now();
 
-    let result = conn.lock().await.execute(
-        "INSERT INTO users (id, username, email, password_hash, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)",
-        params![
-            id.to_string(),
-            user_data.username,
-            user_data.email,
-            password_hash,
-            now.to_rfc3339(),
-            now.to_rfc3339()
-        ],
-    );
-
-    match result {
-        Ok(_) => {
-            let token = create_auth_token(&conn, &id).await.unwrap();
-            (StatusCode::CREATED, Json(AuthToken { token })).into_response()
-        }
-        Err(_) => (StatusCode::BAD_REQUEST, "User already exists").into_response(),
-    }
+    db::with_conn(&state.db, move |conn| {
+        conn.execute(
+            "INSERT INTO users (id, username, email, password_hash, quota_bytes, used_bytes, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                id.to_string(),
+                user_data.username,
+                user_data.email,
+                password_hash,
+                crate::config::default_quota_bytes(),
+                0,
+                now.to_rfc3339(),
+                now.to_rfc3339()
+            ],
+        )
+    })
+    .await
+    .map_err(|_| AppError::Conflict("User already exists".to_string()))?;
+
+    let tokens = create_token_pair(&state.db, &id).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(TokenResponse {
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+        }),
+    ))
 }
 
 pub async fn login_user(
-    State(conn): State<DbConnection>,
+    State(state): State<AppState>,
     Json(login_data): Json<LoginUser>,
-) -> impl IntoResponse {
-    let user_result = conn.lock().await.query_row(
-        "SELECT id, password_hash FROM users WHERE username = ?",
-        [&login_data.username],
-        |row| {
-            Ok((
-                Uuid::parse_str(&row.get::<_, String>(0)?).unwrap(),
-                row.get::<_, String>(1)?,
-            ))
-        },
-    );
-
-    match user_result {
-        Ok((user_id, password_hash)) => {
-            if verify_password(&login_data.password, &password_hash) {
-                let token = create_auth_token(&conn, &user_id).await.unwrap();
-                (StatusCode::OK, Json(AuthToken { token })).into_response()
-            } else {
-                (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response()
-            }
-        }
-        Err(_) => (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response(),
+) -> Result<impl IntoResponse, AppError> {
+    let username = login_data.username.clone();
+    let (user_id, password_hash): (Uuid, String) = db::with_conn(&state.db, move |conn| {
+        conn.query_row(
+            "SELECT id, password_hash FROM users WHERE username = ?",
+            [username],
+            |row| Ok((db::column_uuid(0, &row.get::<_, String>(0)?)?, row.get::<_, String>(1)?)),
+        )
+    })
+    .await
+    .map_err(|_| AppError::Unauthorized)?;
+
+    if !verify_password(&login_data.password, &password_hash) {
+        return Err(AppError::Unauthorized);
+    }
+
+    let tokens = create_token_pair(&state.db, &user_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(TokenResponse {
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+        }),
+    ))
+}
+
+pub async fn refresh_token(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let tokens = rotate_refresh_token(&state.db, &payload.refresh_token)
+        .await
+        .map_err(|_| AppError::InvalidToken)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(TokenResponse {
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct IssueTokenRequest {
+    scopes: Vec<String>,
+    expires_in_secs: i64,
+}
+
+#[derive(Serialize)]
+pub struct IssuedTokenResponse {
+    access_token: String,
+}
+
+/// Mints a narrowly-scoped, short-lived token the caller can hand to a CI job
+/// or a sharing client without delegating its full session.
+pub async fn issue_scoped_token(
+    headers: HeaderMap,
+    Json(payload): Json<IssueTokenRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = authenticate(&headers)?;
+
+    if !payload.scopes.iter().all(|scope| auth.has_scope(scope)) {
+        return Err(AppError::Forbidden(
+            "Cannot grant a scope the caller does not hold".to_string(),
+        ));
     }
+
+    if payload.expires_in_secs <= 0 || payload.expires_in_secs > MAX_SCOPED_TOKEN_TTL_SECS {
+        return Err(AppError::Validation(format!(
+            "expires_in_secs must be between 1 and {MAX_SCOPED_TOKEN_TTL_SECS}"
+        )));
+    }
+
+    let access_token = create_scoped_token(&auth.user_id, &payload.scopes, payload.expires_in_secs)
+        .map_err(|_| AppError::Validation("Failed to issue token".to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(IssuedTokenResponse { access_token })))
 }
 
 pub async fn upload_file(
-    State(conn): State<DbConnection>,
+    State(state): State<AppState>,
     headers: HeaderMap,
     mut multipart: Multipart,
-) -> impl IntoResponse {
-    let token = headers
-        .get("Authorization")
-        .and_then(|value| value.to_str().ok());
-
-    let user_id = match token {
-        Some(token) => match verify_auth_token(&conn, token).await {
-            Ok(user_id) => user_id,
-            Err(_) => return (StatusCode::UNAUTHORIZED, "Invalid token").into_response(),
-        },
-        None => return (StatusCode::UNAUTHORIZED, "Missing token").into_response(),
-    };
-
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let filename = field.file_name().unwrap().to_string();
-        let data = field.bytes().await.unwrap();
+) -> Result<impl IntoResponse, AppError> {
+    let auth = authenticate(&headers)?;
+    require_scope(&auth, SCOPE_FILES_WRITE)?;
+    let user_id = auth.user_id;
 
-        let file_path = format!("files/{}/{}", user_id, filename);
-        tokio::fs::create_dir_all(format!("files/{}", user_id))
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|err| AppError::Validation(err.to_string()))?
+    {
+        let filename = field
+            .file_name()
+            .ok_or_else(|| AppError::Validation("Missing filename".to_string()))?
+            .to_string();
+        let data = field
+            .bytes()
             .await
-            .unwrap();
-        tokio::fs::write(&file_path, &data).await.unwrap();
+            .map_err(|err| AppError::Validation(err.to_string()))?;
+        let size_bytes = data.len() as u64;
+
+        let user_id_str = user_id.to_string();
+
+        // An upload with the same filename replaces the existing file, so its
+        // bytes are freed from the quota. The freed size and the quota check
+        // are read and applied in the single UPDATE below rather than a
+        // separate SELECT-then-UPDATE, so two concurrent uploads for this
+        // user can't both read the same stale `used_bytes` and both pass.
+        let filename_clone = filename.clone();
+        let quota_ok: bool = db::with_conn(&state.db, {
+            let user_id_str = user_id_str.clone();
+            move |conn| {
+                let updated = conn.execute(
+                    "UPDATE users SET used_bytes = used_bytes + ?1 - COALESCE(\
+                         (SELECT size_bytes FROM files WHERE user_id = ?2 AND filename = ?3), 0) \
+                     WHERE id = ?2 AND used_bytes + ?1 - COALESCE(\
+                         (SELECT size_bytes FROM files WHERE user_id = ?2 AND filename = ?3), 0) <= quota_bytes",
+                    params![size_bytes, user_id_str, filename_clone],
+                )?;
+                Ok(updated > 0)
+            }
+        })
+        .await?;
+
+        if !quota_ok {
+            return Err(AppError::PayloadTooLarge("Storage quota exceeded".to_string()));
+        }
+
+        let filename_clone = filename.clone();
+        let existing: Option<(Uuid, String)> = db::with_conn(&state.db, {
+            let user_id_str = user_id_str.clone();
+            move |conn| {
+                conn.query_row(
+                    "SELECT id, file_path FROM files WHERE user_id = ? AND filename = ?",
+                    params![user_id_str, filename_clone],
+                    |row| Ok((db::column_uuid(0, &row.get::<_, String>(0)?)?, row.get(1)?)),
+                )
+            }
+        })
+        .await
+        .ok();
+
+        if let Some((existing_id, existing_path)) = &existing {
+            delete_file_variants(&state, existing_id, existing_path).await;
+            let existing_id = existing_id.to_string();
+            db::with_conn(&state.db, move |conn| {
+                conn.execute("DELETE FROM files WHERE id = ?", [existing_id])
+            })
+            .await?;
+        }
 
         let id = Uuid::new_v4();
+        let encrypted = crate::crypto::encrypt_file(&data, id.as_bytes());
+
+        let storage_key = format!("{}/{}", user_id, filename);
+        state.storage.put(&storage_key, &encrypted).await?;
+
+        if images::is_image(&filename) {
+            generate_eager_thumbnails(&state, id, &data).await;
+        }
+
         let now = Utc::now();
 
-        conn.lock().await.execute(
-            "INSERT INTO files (id, user_id, filename, file_path, is_public, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
-            params![
-                id.to_string(),
-                user_id.to_string(),
-                filename,
-                file_path,
-                false,
-                now.to_rfc3339(),
-                now.to_rfc3339()
-            ],
-        ).unwrap();
+        db::with_conn(&state.db, move |conn| {
+            conn.execute(
+                "INSERT INTO files (id, user_id, filename, file_path, size_bytes, is_public, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    id.to_string(),
+                    user_id_str,
+                    filename,
+                    storage_key,
+                    size_bytes,
+                    false,
+                    now.to_rfc3339(),
+                    now.to_rfc3339()
+                ],
+            )
+        })
+        .await?;
 
-        return (StatusCode::CREATED, "File uploaded successfully").into_response();
+        return Ok((StatusCode::CREATED, "File uploaded successfully"));
     }
 
-    (StatusCode::BAD_REQUEST, "No file uploaded").into_response()
+    Err(AppError::Validation("No file uploaded".to_string()))
 }
 
-pub async fn get_user_files(
-    State(conn): State<DbConnection>,
-    headers: HeaderMap,
-) -> impl IntoResponse {
-    let token = headers
-        .get("Authorization")
-        .and_then(|value| value.to_str().ok());
-
-    let user_id = match token {
-        Some(token) => match verify_auth_token(&conn, token).await {
-            Ok(user_id) => user_id,
-            Err(_) => return (StatusCode::UNAUTHORIZED, "Invalid token").into_response(),
-        },
-        None => return (StatusCode::UNAUTHORIZED, "Missing token").into_response(),
-    };
+/// Best-effort pre-generation of the standard thumbnail sizes for a newly
+/// uploaded image, stored under the same cache keys `serve_public_file` reads
+/// from so a request for one of them is served from cache on the first try.
+/// Failures (unsupported format, encode error) are swallowed: the image
+/// still serves correctly, just via the slower on-demand path.
+async fn generate_eager_thumbnails(state: &AppState, file_id: Uuid, original: &[u8]) {
+    for &(w, h) in images::THUMBNAIL_SIZES {
+        let params = images::thumbnail_params(w, h);
+        let cache_key = images::cache_key(&file_id.to_string(), &params);
 
-    let conn = conn.lock().await;
-    let mut stmt = match conn.prepare("SELECT id, user_id, filename, file_path, is_public, public_url, created_at, updated_at FROM files WHERE user_id = ?") {
-        Ok(stmt) => stmt,
-        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to prepare statement").into_response(),
-    };
+        let Ok((transformed, _mime_type)) = images::transform(original, &params) else {
+            continue;
+        };
 
-    let files_result = stmt.query_map([user_id.to_string()], |row| {
-        Ok(File {
-            id: Uuid::parse_str(&row.get::<_, String>(0)?).unwrap(),
-            user_id: Uuid::parse_str(&row.get::<_, String>(1)?).unwrap(),
-            filename: row.get(2)?,
-            file_path: row.get(3)?,
-            is_public: row.get(4)?,
-            public_url: row.get(5)?,
-            created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(6)?).unwrap().with_timezone(&Utc),
-            updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(7)?).unwrap().with_timezone(&Utc),
-        })
-    });
-
-    match files_result {
-        Ok(files) => {
-            let collected: Result<Vec<_>, _> = files.collect();
-            match collected {
-                Ok(files) => (StatusCode::OK, Json(files)).into_response(),
-                Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to process files").into_response(),
-            }
-        },
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch files").into_response(),
+        let encrypted = crate::crypto::encrypt_file(&transformed, cache_key.as_bytes());
+        let _ = state.storage.put(&cache_key, &encrypted).await;
     }
 }
 
+/// Best-effort removal of a file's original bytes and any on-demand transform
+/// variants cached under its id (see `images::cache_key`).
+async fn delete_file_variants(state: &AppState, file_id: &Uuid, storage_key: &str) {
+    let _ = state.storage.delete(storage_key).await;
+    let _ = state.storage.delete_prefix(&format!("{file_id}@")).await;
+}
 
-pub async fn make_file_public(
-    State(conn): State<DbConnection>,
+pub async fn delete_file(
+    State(state): State<AppState>,
     headers: HeaderMap,
     Path(file_id): Path<Uuid>,
-) -> impl IntoResponse {
-    let token = headers
-        .get("Authorization")
-        .and_then(|value| value.to_str().ok());
-
-    let user_id = match token {
-        Some(token) => match verify_auth_token(&conn, token).await {
-            Ok(user_id) => user_id,
-            Err(_) => return (StatusCode::UNAUTHORIZED, "Invalid token").into_response(),
-        },
-        None => return (StatusCode::UNAUTHORIZED, "Missing token").into_response(),
-    };
+) -> Result<impl IntoResponse, AppError> {
+    let auth = authenticate(&headers)?;
+    require_scope(&auth, SCOPE_FILES_WRITE)?;
+    let user_id = auth.user_id;
 
-    let public_url = format!("/public/{}", file_id);
-    let result = conn.lock().await.execute(
-        "UPDATE files SET is_public = ?, public_url = ? WHERE id = ? AND user_id = ?",
-        params![true, public_url, file_id.to_string(), user_id.to_string()],
-    );
-
-    match result {
-        Ok(updated) if updated > 0 => {
-            let file = conn.lock().await.query_row(
-                "SELECT id, user_id, filename, file_path, is_public, public_url, created_at, updated_at FROM files WHERE id = ?",
-                [file_id.to_string()],
-                |row| {
-                    Ok(File {
-                        id: Uuid::parse_str(&row.get::<_, String>(0)?).unwrap(),
-                        user_id: Uuid::parse_str(&row.get::<_, String>(1)?).unwrap(),
-                        filename: row.get(2)?,
-                        file_path: row.get(3)?,
-                        is_public: row.get(4)?,
-                        public_url: row.get(5)?,
-                        created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(6)?).unwrap().with_timezone(&Utc),
-                        updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(7)?).unwrap().with_timezone(&Utc),
-                    })
-                },
-            ).unwrap();
-            (StatusCode::OK, Json(file)).into_response()
+    let file_id_str = file_id.to_string();
+    let user_id_str = user_id.to_string();
+    let (size_bytes, storage_key): (u64, String) = db::with_conn(&state.db, {
+        let file_id_str = file_id_str.clone();
+        let user_id_str = user_id_str.clone();
+        move |conn| {
+            conn.query_row(
+                "SELECT size_bytes, file_path FROM files WHERE id = ? AND user_id = ?",
+                params![file_id_str, user_id_str],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
         }
-        _ => (StatusCode::NOT_FOUND, "File not found").into_response(),
-    }
+    })
+    .await
+    .map_err(|_| AppError::NotFound)?;
+
+    delete_file_variants(&state, &file_id, &storage_key).await;
+
+    db::with_conn(&state.db, move |conn| {
+        conn.execute("DELETE FROM files WHERE id = ?", [file_id_str])?;
+        conn.execute(
+            "UPDATE users SET used_bytes = MAX(used_bytes - ?, 0) WHERE id = ?",
+            params![size_bytes, user_id_str],
+        )
+    })
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub async fn serve_public_file(
-    State(conn): State<DbConnection>,
+#[derive(Serialize)]
+pub struct UsageResponse {
+    used_bytes: u64,
+    quota_bytes: u64,
+    percent_used: f64,
+}
+
+pub async fn get_usage(State(state): State<AppState>, headers: HeaderMap) -> Result<impl IntoResponse, AppError> {
+    let auth = authenticate(&headers)?;
+    require_scope(&auth, SCOPE_FILES_READ)?;
+    let user_id = auth.user_id;
+
+    let (used_bytes, quota_bytes): (u64, u64) = db::with_conn(&state.db, move |conn| {
+        conn.query_row(
+            "SELECT used_bytes, quota_bytes FROM users WHERE id = ?",
+            [user_id.to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    })
+    .await
+    .map_err(|_| AppError::NotFound)?;
+
+    let percent_used = if quota_bytes > 0 {
+        used_bytes as f64 / quota_bytes as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    Ok(Json(UsageResponse {
+        used_bytes,
+        quota_bytes,
+        percent_used,
+    }))
+}
+
+pub async fn get_user_files(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = authenticate(&headers)?;
+    require_scope(&auth, SCOPE_FILES_READ)?;
+    let user_id = auth.user_id;
+
+    let files: Vec<File> = db::with_conn(&state.db, move |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, filename, file_path, size_bytes, is_public, public_url, public_slug, created_at, updated_at FROM files WHERE user_id = ?",
+        )?;
+
+        stmt.query_map([user_id.to_string()], |row| {
+            Ok(File {
+                id: db::column_uuid(0, &row.get::<_, String>(0)?)?,
+                user_id: db::column_uuid(1, &row.get::<_, String>(1)?)?,
+                filename: row.get(2)?,
+                file_path: row.get(3)?,
+                size_bytes: row.get(4)?,
+                is_public: row.get(5)?,
+                public_url: row.get(6)?,
+                public_slug: row.get(7)?,
+                created_at: db::column_timestamp(8, &row.get::<_, String>(8)?)?,
+                updated_at: db::column_timestamp(9, &row.get::<_, String>(9)?)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()
+    })
+    .await?;
+
+    Ok(Json(files))
+}
+
+pub async fn make_file_public(
+    State(state): State<AppState>,
+    headers: HeaderMap,
     Path(file_id): Path<Uuid>,
-) -> impl IntoResponse {
-    let file_result = conn.lock().await.query_row(
-        "SELECT file_path FROM files WHERE id = ? AND is_public = ?",
-        params![file_id.to_string(), true],
-        |row| row.get::<_, String>(0),
-    );
-
-    match file_result {
-        Ok(file_path) => {
-            let path = PathBuf::from(&file_path);
-            match fs::File::open(&path).await {
-                Ok(file) => {
-                    let stream = ReaderStream::new(file);
-                    let body = Body::from_stream(stream);
-
-                    let mime_type = mime_guess::from_path(&path).first_or_octet_stream();
-
-                    Response::builder()
-                        .status(StatusCode::OK)
-                        .header(header::CONTENT_TYPE, mime_type.as_ref())
-                        .header(header::CACHE_CONTROL, "public, max-age=31536000")
-                        .body(body)
-                        .unwrap()
+) -> Result<impl IntoResponse, AppError> {
+    let auth = authenticate(&headers)?;
+    require_scope(&auth, SCOPE_FILES_PUBLIC)?;
+    let user_id = auth.user_id;
+
+    let file_id_str = file_id.to_string();
+    let user_id_str = user_id.to_string();
+
+    let file = db::with_conn(&state.db, move |conn| {
+        // Retry on the rare collision of the randomly-salted slug rather than
+        // widening the salt, since a short slug is the whole point here.
+        let mut attempts = 0;
+        loop {
+            let slug = crate::slug::generate_public_slug();
+            let public_url = format!("/p/{}", slug);
+            let result = conn.execute(
+                "UPDATE files SET is_public = ?, public_url = ?, public_slug = ? WHERE id = ? AND user_id = ?",
+                params![true, public_url, slug, file_id_str.clone(), user_id_str.clone()],
+            );
+
+            match result {
+                Ok(0) => return Err(rusqlite::Error::QueryReturnedNoRows),
+                Ok(_) => break,
+                Err(rusqlite::Error::SqliteFailure(err, _))
+                    if err.code == rusqlite::ErrorCode::ConstraintViolation && attempts < 5 =>
+                {
+                    attempts += 1;
                 }
-                Err(_) => (StatusCode::NOT_FOUND, "File not found").into_response(),
+                Err(err) => return Err(err),
             }
         }
-        Err(_) => (StatusCode::NOT_FOUND, "File not found").into_response(),
+
+        conn.query_row(
+            "SELECT id, user_id, filename, file_path, size_bytes, is_public, public_url, public_slug, created_at, updated_at FROM files WHERE id = ?",
+            [file_id_str],
+            |row| {
+                Ok(File {
+                    id: db::column_uuid(0, &row.get::<_, String>(0)?)?,
+                    user_id: db::column_uuid(1, &row.get::<_, String>(1)?)?,
+                    filename: row.get(2)?,
+                    file_path: row.get(3)?,
+                    size_bytes: row.get(4)?,
+                    is_public: row.get(5)?,
+                    public_url: row.get(6)?,
+                    public_slug: row.get(7)?,
+                    created_at: db::column_timestamp(8, &row.get::<_, String>(8)?)?,
+                    updated_at: db::column_timestamp(9, &row.get::<_, String>(9)?)?,
+                })
+            },
+        )
+    })
+    .await
+    .map_err(|_| AppError::NotFound)?;
+
+    Ok(Json(file))
+}
+
+async fn read_and_decrypt(state: &AppState, storage_key: &str, associated_data: &[u8]) -> Result<Vec<u8>, AppError> {
+    let mut reader = state
+        .storage
+        .get(storage_key)
+        .await
+        .map_err(|_| AppError::NotFound)?;
+
+    let mut encrypted = Vec::new();
+    reader.read_to_end(&mut encrypted).await?;
+
+    crate::crypto::decrypt_file(&encrypted, associated_data)
+        .map_err(|_| AppError::Validation("Failed to decrypt file".to_string()))
+}
+
+pub async fn serve_public_file(
+    State(state): State<AppState>,
+    Path(slug): Path<String>,
+    Query(params): Query<TransformParams>,
+) -> Result<Response, AppError> {
+    let (file_id, storage_key): (Uuid, String) = db::with_conn(&state.db, move |conn| {
+        conn.query_row(
+            "SELECT id, file_path FROM files WHERE public_slug = ? AND is_public = ?",
+            params![slug, true],
+            |row| Ok((db::column_uuid(0, &row.get::<_, String>(0)?)?, row.get(1)?)),
+        )
+    })
+    .await
+    .map_err(|_| AppError::NotFound)?;
+
+    if images::is_image(&storage_key) && !params.is_empty() {
+        let cache_key = images::cache_key(&file_id.to_string(), &params);
+
+        if let Ok(cached) = read_and_decrypt(&state, &cache_key, cache_key.as_bytes()).await {
+            let mime_type = params.format.map(|format| format.mime_type()).unwrap_or("image/jpeg");
+            return Ok(serve_bytes(cached, mime_type));
+        }
+
+        let original = read_and_decrypt(&state, &storage_key, file_id.as_bytes()).await?;
+
+        let (transformed, mime_type) = images::transform(&original, &params)
+            .map_err(|_| AppError::Validation("Failed to transform image".to_string()))?;
+
+        let encrypted = crate::crypto::encrypt_file(&transformed, cache_key.as_bytes());
+        let _ = state.storage.put(&cache_key, &encrypted).await;
+
+        return Ok(serve_bytes(transformed, mime_type));
     }
+
+    let plaintext = read_and_decrypt(&state, &storage_key, file_id.as_bytes()).await?;
+    let mime_type = mime_guess::from_path(&storage_key).first_or_octet_stream();
+    Ok(serve_bytes(plaintext, mime_type.as_ref()))
+}
+
+fn serve_bytes(bytes: Vec<u8>, mime_type: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, mime_type)
+        .header(header::CACHE_CONTROL, "public, max-age=31536000")
+        .body(Body::from(bytes))
+        .unwrap()
 }
@@ -0,0 +1,114 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use serde::Deserialize;
+
+pub fn is_image(filename: &str) -> bool {
+    mime_guess::from_path(filename)
+        .first()
+        .map(|mime| mime.type_() == mime_guess::mime::IMAGE)
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransformParams {
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    pub fit: Option<Fit>,
+    pub format: Option<OutputFormat>,
+}
+
+impl TransformParams {
+    pub fn is_empty(&self) -> bool {
+        self.w.is_none() && self.h.is_none() && self.fit.is_none() && self.format.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    Cover,
+    Contain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl OutputFormat {
+    fn image_format(self) -> ImageFormat {
+        match self {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::Webp => ImageFormat::WebP,
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+        }
+    }
+}
+
+/// Thumbnail sizes pre-generated on upload, so the first public request for a
+/// common size hits the transform cache instead of paying for a synchronous
+/// resize. Keyed through `cache_key` like any other transform, so
+/// `serve_public_file` can't tell an eager thumbnail apart from one it
+/// generated itself on demand.
+pub const THUMBNAIL_SIZES: &[(u32, u32)] = &[(128, 128), (320, 240), (640, 480)];
+
+/// Builds the `TransformParams` for a standard thumbnail size, matching the
+/// defaults `serve_public_file` falls back to (cover fit, JPEG output).
+pub fn thumbnail_params(w: u32, h: u32) -> TransformParams {
+    TransformParams {
+        w: Some(w),
+        h: Some(h),
+        fit: Some(Fit::Cover),
+        format: Some(OutputFormat::Jpeg),
+    }
+}
+
+/// Derives a stable cache key for a transform so repeat requests for the same
+/// file id + params hit the cached variant instead of re-encoding.
+pub fn cache_key(file_id: &str, params: &TransformParams) -> String {
+    format!(
+        "{}@w{}_h{}_fit{:?}_fmt{:?}",
+        file_id,
+        params.w.unwrap_or(0),
+        params.h.unwrap_or(0),
+        params.fit.unwrap_or(Fit::Cover),
+        params.format.unwrap_or(OutputFormat::Jpeg)
+    )
+}
+
+/// Resizes/re-encodes `bytes` per `params`, returning the encoded image and its mime type.
+pub fn transform(bytes: &[u8], params: &TransformParams) -> image::ImageResult<(Vec<u8>, &'static str)> {
+    let img = image::load_from_memory(bytes)?;
+    let resized = resize(img, params.w, params.h, params.fit.unwrap_or(Fit::Cover));
+
+    let format = params.format.unwrap_or(OutputFormat::Jpeg);
+    let mut out = Vec::new();
+    resized.write_to(&mut std::io::Cursor::new(&mut out), format.image_format())?;
+
+    Ok((out, format.mime_type()))
+}
+
+fn resize(img: DynamicImage, w: Option<u32>, h: Option<u32>, fit: Fit) -> DynamicImage {
+    let (target_w, target_h) = match (w, h) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, w * img.height() / img.width().max(1)),
+        (None, Some(h)) => (h * img.width() / img.height().max(1), h),
+        (None, None) => return img,
+    };
+
+    match fit {
+        Fit::Cover => img.resize_to_fill(target_w, target_h, FilterType::Lanczos3),
+        Fit::Contain => img.resize(target_w, target_h, FilterType::Lanczos3),
+    }
+}
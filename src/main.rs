@@ -1,27 +1,51 @@
 mod auth;
+mod config;
+mod crypto;
 mod db;
+mod error;
 mod handlers;
+mod images;
 mod models;
+mod slug;
+mod storage;
 
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
+use db::DbPool;
+use std::sync::Arc;
+use storage::{LocalStorage, StorageBackend};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: DbPool,
+    pub storage: Arc<dyn StorageBackend>,
+}
 
 #[tokio::main]
 async fn main() {
+    crypto::ensure_key_configured();
+    auth::ensure_jwt_secret_configured();
+
     let conn = db::establish_connection().expect("Failed to establish database connection");
+    let storage: Arc<dyn StorageBackend> = Arc::new(LocalStorage::new("files"));
+    let state = AppState { db: conn, storage };
 
     let app = Router::new()
         .route("/register", post(handlers::register_user))
         .route("/login", post(handlers::login_user))
+        .route("/refresh", post(handlers::refresh_token))
+        .route("/tokens", post(handlers::issue_scoped_token))
         .route("/upload", post(handlers::upload_file))
         .route("/files", get(handlers::get_user_files))
+        .route("/files/:file_id", delete(handlers::delete_file))
         .route("/files/:file_id/make_public", post(handlers::make_file_public))
-        .route("/public/:file_id", get(handlers::serve_public_file))
-        .with_state(conn);
+        .route("/p/:slug", get(handlers::serve_public_file))
+        .route("/usage", get(handlers::get_usage))
+        .with_state(state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
     println!("Server running on http://0.0.0.0:3000");
     axum::serve(listener, app).await.unwrap();
-}
\ No newline at end of file
+}
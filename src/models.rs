@@ -26,8 +26,10 @@ pub struct File {
     pub user_id: Uuid,
     pub filename: String,
     pub file_path: String,
+    pub size_bytes: u64,
     pub is_public: bool,
     pub public_url: Option<String>,
+    pub public_slug: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -37,6 +39,8 @@ pub struct NewFile<'a> {
     pub user_id: Uuid,
     pub filename: &'a str,
     pub file_path: &'a str,
+    pub size_bytes: u64,
     pub is_public: bool,
     pub public_url: Option<&'a str>,
+    pub public_slug: Option<&'a str>,
 }
\ No newline at end of file
@@ -0,0 +1,10 @@
+use rand::Rng;
+use sqids::Sqids;
+
+/// Encodes a random salt into a short, collision-resistant public slug so
+/// share links don't leak the file's internal UUID primary key.
+pub fn generate_public_slug() -> String {
+    let sqids = Sqids::default();
+    let salt: u64 = rand::thread_rng().gen();
+    sqids.encode(&[salt]).unwrap_or_else(|_| salt.to_string())
+}
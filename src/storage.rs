@@ -0,0 +1,92 @@
+use async_trait::async_trait;
+use std::path::{Component, Path, PathBuf};
+use tokio::io::AsyncRead;
+
+/// Abstracts where file bytes actually live so deployments can swap local disk
+/// for an object store (S3, MinIO, ...) without touching handler code.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn put(&self, key: &str, bytes: &[u8]) -> std::io::Result<()>;
+    async fn get(&self, key: &str) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>>;
+    async fn delete(&self, key: &str) -> std::io::Result<()>;
+    async fn exists(&self, key: &str) -> std::io::Result<bool>;
+    /// Deletes every key starting with `prefix`, e.g. the cached transform
+    /// variants derived from a single uploaded file.
+    async fn delete_prefix(&self, prefix: &str) -> std::io::Result<()>;
+}
+
+/// Stores file bytes under a root directory on local disk, keyed by storage key.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Maps a storage key to an on-disk path, rejecting keys that would
+    /// escape `root` (`..`/absolute components) so a malicious file name
+    /// can't be used to read or write outside the storage directory.
+    fn path_for(&self, key: &str) -> std::io::Result<PathBuf> {
+        let key_path = Path::new(key);
+        if key_path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid storage key: {key:?}"),
+            ));
+        }
+
+        Ok(self.root.join(key_path))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn put(&self, key: &str, bytes: &[u8]) -> std::io::Result<()> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = Path::new(&path).parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, bytes).await
+    }
+
+    async fn get(&self, key: &str) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let file = tokio::fs::File::open(self.path_for(key)?).await?;
+        Ok(Box::new(file))
+    }
+
+    async fn delete(&self, key: &str) -> std::io::Result<()> {
+        tokio::fs::remove_file(self.path_for(key)?).await
+    }
+
+    async fn exists(&self, key: &str) -> std::io::Result<bool> {
+        Ok(tokio::fs::metadata(self.path_for(key)?).await.is_ok())
+    }
+
+    /// Only entries in the directory that holds the prefix's last path
+    /// component are matched; keys nested inside a matching subdirectory are
+    /// not deleted, and such a subdirectory makes the call return an error.
+    async fn delete_prefix(&self, prefix: &str) -> std::io::Result<()> {
+        let full_prefix = self.path_for(prefix)?;
+        let dir = full_prefix.parent().unwrap_or(&self.root).to_path_buf();
+        let file_prefix = full_prefix.file_name().and_then(|name| name.to_str()).unwrap_or("").to_string();
+
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with(&file_prefix) {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+
+        Ok(())
+    }
+}